@@ -0,0 +1,186 @@
+use std::str::FromStr;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Named value conversions applied to a captured field before it is pushed
+/// as a metric value. Mirrors the `"int"`, `"float"`, ... conversion names
+/// used by observability pipelines so collector authors don't need to learn
+/// a bespoke vocabulary.
+#[derive(Clone, Debug)]
+pub enum Conversion {
+    Bytes,
+    Integer,
+    Float,
+    Boolean,
+    Timestamp,
+    TimestampFmt(String),
+}
+
+impl FromStr for Conversion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(fmt) = s.strip_prefix("timestamp|") {
+            return Ok(Conversion::TimestampFmt(fmt.to_string()));
+        }
+        match s {
+            "bytes" => Ok(Conversion::Bytes),
+            "int" | "integer" => Ok(Conversion::Integer),
+            "float" => Ok(Conversion::Float),
+            "bool" | "boolean" => Ok(Conversion::Boolean),
+            "timestamp" => Ok(Conversion::Timestamp),
+            other => Err(format!("Unknown conversion name: {}", other)),
+        }
+    }
+}
+
+/// Parses a human-readable size like `"1.5GB"` or `"512KiB"` into a raw byte
+/// count. A bare number with no unit suffix is treated as already being in
+/// bytes, so collectors that already emit raw byte counts keep working.
+fn parse_byte_size(normalized: &str) -> Result<f64, String> {
+    let split_at = normalized.find(|c: char| c.is_alphabetic()).unwrap_or(normalized.len());
+    let (number, unit) = normalized.split_at(split_at);
+    let number : f64 = number.trim().parse()
+        .map_err(|e| format!("Unable to convert '{}' to a byte size: {}", normalized, e))?;
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1.0,
+        "KB" => 1_000.0,
+        "MB" => 1_000_000.0,
+        "GB" => 1_000_000_000.0,
+        "TB" => 1_000_000_000_000.0,
+        "KIB" => 1024.0,
+        "MIB" => 1024.0 * 1024.0,
+        "GIB" => 1024.0 * 1024.0 * 1024.0,
+        "TIB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        other => return Err(format!("Unknown byte size unit '{}' in '{}'", other, normalized)),
+    };
+    Ok(number * multiplier)
+}
+
+impl Conversion {
+    /// Applies this conversion to a raw captured string, normalizing the
+    /// decimal-comma locale quirk the old hand-written handlers worked
+    /// around ad hoc, and returns the metric value as `f64`.
+    pub fn apply(&self, raw: &str) -> Result<f64, String> {
+        let normalized = raw.trim().replace(",", ".");
+        match self {
+            Conversion::Float => normalized.parse::<f64>()
+                .map_err(|e| format!("Unable to convert '{}' to float: {}", raw, e)),
+            Conversion::Bytes => parse_byte_size(&normalized),
+            Conversion::Integer => normalized.parse::<i64>()
+                .map(|v| v as f64)
+                .map_err(|e| format!("Unable to convert '{}' to integer: {}", raw, e)),
+            Conversion::Boolean => match normalized.to_lowercase().as_str() {
+                "1" | "true" | "yes" => Ok(1.0),
+                "0" | "false" | "no" => Ok(0.0),
+                _ => Err(format!("Unable to convert '{}' to boolean", raw)),
+            },
+            Conversion::Timestamp => chrono::NaiveDateTime::parse_from_str(raw.trim(), "%Y-%m-%d %H:%M:%S")
+                .map(|dt| dt.timestamp() as f64)
+                .map_err(|e| format!("Unable to convert '{}' to timestamp: {}", raw, e)),
+            Conversion::TimestampFmt(fmt) => chrono::NaiveDateTime::parse_from_str(raw.trim(), fmt)
+                .map(|dt| dt.timestamp() as f64)
+                .map_err(|e| format!("Unable to convert '{}' to timestamp with format '{}': {}", raw, fmt, e)),
+        }
+    }
+}
+
+/// How a collector decides whether a line of command output is relevant.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LineMatcher {
+    Prefix { value: String },
+    Regex { value: String },
+}
+
+impl LineMatcher {
+    /// Checks a line against this matcher. For the `Regex` variant,
+    /// `compiled` must be the regex precompiled from this matcher's `value`
+    /// by [`load_collectors_config`] — there is no pattern to compile here.
+    pub fn matches(&self, line: &str, compiled: Option<&Regex>) -> bool {
+        match self {
+            LineMatcher::Prefix { value } => line.starts_with(value.as_str()),
+            LineMatcher::Regex { .. } => compiled.map(|re| re.is_match(line)).unwrap_or(false),
+        }
+    }
+}
+
+/// Maps one named capture group from `pattern` onto a label name.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LabelCapture {
+    pub capture: String,
+    pub label: String,
+}
+
+/// Maps one named capture group from `pattern` onto the metric value,
+/// via a named conversion (see [`Conversion::from_str`]).
+#[derive(Clone, Debug, Deserialize)]
+pub struct ValueCapture {
+    pub capture: String,
+    pub conversion: String,
+}
+
+fn default_metric_kind() -> String {
+    "gauge".to_string()
+}
+
+/// A single TOML-declared scraper: a command to run, a matcher to select
+/// relevant lines, and a set of field captures describing how to turn a
+/// matched line into a labelled metric sample.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CollectorConfig {
+    pub name: String,
+    pub metric_id: String,
+    /// `# HELP` text for this metric in the Prometheus exposition output.
+    #[serde(default)]
+    pub help: String,
+    /// `# TYPE` for this metric, e.g. "gauge" or "counter". Defaults to "gauge".
+    #[serde(default = "default_metric_kind")]
+    pub kind: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub matcher: LineMatcher,
+    /// `matcher`'s regex (when it is the `Regex` variant) compiled once at
+    /// load time, so a collector doesn't recompile it on every scraped line.
+    #[serde(skip)]
+    pub compiled_matcher: Option<Regex>,
+    pub pattern: String,
+    /// `pattern` compiled once at load time, so a collector doesn't recompile
+    /// its regex on every scraped line.
+    #[serde(skip)]
+    pub compiled_pattern: Option<Regex>,
+    #[serde(default)]
+    pub labels: Vec<LabelCapture>,
+    pub value: ValueCapture,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CollectorsConfig {
+    #[serde(default)]
+    pub collectors: Vec<CollectorConfig>,
+}
+
+/// Loads and parses a collectors TOML file. Returns an empty config (no
+/// error) when the file does not exist, so running without one is the
+/// same as before this feature existed.
+pub fn load_collectors_config(path: &str) -> Result<CollectorsConfig, String> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(CollectorsConfig { collectors: vec![] });
+    }
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read collectors config '{}': {}", path, e))?;
+    let mut parsed : CollectorsConfig = toml::from_str(&raw)
+        .map_err(|e| format!("Unable to parse collectors config '{}': {}", path, e))?;
+    for collector in &mut parsed.collectors {
+        let re = Regex::new(&collector.pattern)
+            .map_err(|e| format!("Collector '{}' has an invalid pattern: {}", collector.name, e))?;
+        collector.compiled_pattern = Some(re);
+        if let LineMatcher::Regex { value } = &collector.matcher {
+            let matcher_re = Regex::new(value)
+                .map_err(|e| format!("Collector '{}' has an invalid matcher pattern: {}", collector.name, e))?;
+            collector.compiled_matcher = Some(matcher_re);
+        }
+    }
+    Ok(parsed)
+}
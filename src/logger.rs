@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use async_std::sync::Mutex;
+use async_std::task;
+
+/// Severity of a log record, most to least severe. Declaration order
+/// doubles as the filter ordering: a record is kept when it is at least as
+/// severe as the configured level.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "ERROR"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Debug => write!(f, "DEBUG"),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            other => Err(format!("Unknown log level: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// Retains the last `capacity` log records at or above the configured
+/// level, so a daemonized scraper can be inspected over `/logs` instead of
+/// only through stderr.
+pub struct BufferLogger {
+    level: LogLevel,
+    capacity: usize,
+    records: VecDeque<LogRecord>,
+}
+
+impl BufferLogger {
+    pub fn new(level: LogLevel, capacity: usize) -> BufferLogger {
+        BufferLogger {
+            level,
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn record(&mut self, level: LogLevel, message: String) {
+        eprintln!("[{}] {}", level, message);
+        if level > self.level || self.capacity == 0 {
+            return;
+        }
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(LogRecord { level, message });
+    }
+
+    pub fn records(&self) -> &VecDeque<LogRecord> {
+        &self.records
+    }
+}
+
+pub type SharedLogger = Arc<Mutex<BufferLogger>>;
+
+pub fn new_shared_logger(level: LogLevel, capacity: usize) -> SharedLogger {
+    Arc::new(Mutex::new(BufferLogger::new(level, capacity)))
+}
+
+fn log(logger: &SharedLogger, level: LogLevel, message: String) {
+    let logger = logger.clone();
+    task::block_on(async move {
+        let mut lock = logger.lock().await;
+        lock.record(level, message);
+    });
+}
+
+pub fn log_error(logger: &SharedLogger, message: String) {
+    log(logger, LogLevel::Error, message);
+}
+
+pub fn log_warn(logger: &SharedLogger, message: String) {
+    log(logger, LogLevel::Warn, message);
+}
+
+#[allow(dead_code)]
+pub fn log_info(logger: &SharedLogger, message: String) {
+    log(logger, LogLevel::Info, message);
+}
+
+#[allow(dead_code)]
+pub fn log_debug(logger: &SharedLogger, message: String) {
+    log(logger, LogLevel::Debug, message);
+}
+
+pub fn render_logs(records: &VecDeque<LogRecord>) -> String {
+    records.iter()
+        .map(|r| format!("[{}] {}", r.level, r.message))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -1,67 +1,191 @@
+mod config;
+mod remote_write;
+mod proc_collectors;
+mod logger;
+
 use std::process::Command;
 use std::str;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
 use async_std::sync::Mutex;
 use async_std::task;
 use futures::stream::{FuturesUnordered, StreamExt};
 use std::thread::sleep;
 use std::time::Duration;
 use std::io::Cursor;
-use futures::join;
 use tiny_http::Response;
+use config::{CollectorConfig, Conversion};
+use logger::SharedLogger;
+
+static COLLECTORS_CONFIG_PATH : &str = "collectors.toml";
+
+pub(crate) static CPU_USER_ID : &str = "cpu_user";
+pub(crate) static CPU_IDLE_ID : &str = "cpu_idle";
+pub(crate) static CPU_SYSTEM_ID : &str = "cpu_system";
+pub(crate) static CPU_NICE_ID : &str = "cpu_nice";
+pub(crate) static MEM_TOTAL_ID : &str = "mem_total";
+pub(crate) static MEM_FREE_ID : &str = "mem_free";
+pub(crate) static MEM_USED_ID : &str = "mem_used";
+pub(crate) static MEM_BUFFERED_ID : &str = "mem_buffered";
+pub(crate) static PROC_CPU_ID : &str = "proc_cpu";
+pub(crate) static PROC_MEM_ID : &str = "proc_mem";
+pub(crate) static NETSTAT_INFO_ID : &str = "netstat_info";
+pub(crate) static COLLECTOR_ERROR_ID : &str = "collector_error";
+
+#[derive(Clone, Copy, PartialEq)]
+enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetricKind::Gauge => write!(f, "gauge"),
+            MetricKind::Counter => write!(f, "counter"),
+        }
+    }
+}
 
-static CPU_USER_ID : &str = "cpu_user";
-static CPU_IDLE_ID : &str = "cpu_idle";
-static CPU_SYSTEM_ID : &str = "cpu_system";
-static CPU_NICE_ID : &str = "cpu_nice";
-static MEM_TOTAL_ID : &str = "mem_total";
-static MEM_FREE_ID : &str = "mem_free";
-static MEM_USED_ID : &str = "mem_used";
-static MEM_BUFFERED_ID : &str = "mem_buffered";
-static PROC_CPU_ID : &str = "proc_cpu";
-static PROC_MEM_ID : &str = "proc_mem";
-static NETSTAT_INFO_ID : &str = "netstat_info";
-
-struct DataHolder {
-    data: Vec<(String, f64)>,
+impl FromStr for MetricKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gauge" => Ok(MetricKind::Gauge),
+            "counter" => Ok(MetricKind::Counter),
+            other => Err(format!("Unknown metric kind: {}", other)),
+        }
+    }
+}
+
+fn metric_kind(id: &str) -> MetricKind {
+    match id {
+        _ if id == NETSTAT_INFO_ID => MetricKind::Counter,
+        _ => MetricKind::Gauge,
+    }
+}
+
+fn metric_help(id: &str) -> &'static str {
+    match id {
+        _ if id == CPU_USER_ID => "Percentage of CPU time spent in user space",
+        _ if id == CPU_IDLE_ID => "Percentage of CPU time spent idle",
+        _ if id == CPU_SYSTEM_ID => "Percentage of CPU time spent in kernel space",
+        _ if id == CPU_NICE_ID => "Percentage of CPU time spent on niced processes",
+        _ if id == MEM_TOTAL_ID => "Total system memory in MiB",
+        _ if id == MEM_FREE_ID => "Free system memory in MiB",
+        _ if id == MEM_USED_ID => "Used system memory in MiB",
+        _ if id == MEM_BUFFERED_ID => "Memory used for buffers and cache in MiB",
+        _ if id == PROC_CPU_ID => "Per-process CPU usage percentage",
+        _ if id == PROC_MEM_ID => "Per-process resident memory (MiB when read from /proc, top's RES column otherwise)",
+        _ if id == NETSTAT_INFO_ID => "Cumulative counters reported by netstat -s",
+        _ if id == COLLECTOR_ERROR_ID => "Set to 1 when a collector's underlying command could not be started, e.g. because it is not installed",
+        _ => "",
+    }
+}
+
+/// A single labelled measurement, ready to be rendered as a Prometheus sample
+/// or serialized into InfluxDB line protocol for remote write.
+pub(crate) struct Sample {
+    pub(crate) name: &'static str,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) value: f64,
+    pub(crate) kind: MetricKind,
+    pub(crate) help: String,
+}
+
+pub(crate) struct DataHolder {
+    data: Vec<Sample>,
 }
 
 impl DataHolder {
-    pub fn new() -> DataHolder {
+    pub(crate) fn new() -> DataHolder {
         return DataHolder {
             data: vec![],
         };
     }
 
-    pub fn get_data(&self) -> &Vec<(String, f64)> {
+    pub(crate) fn get_data(&self) -> &Vec<Sample> {
         return &self.data;
     }
 
-    pub fn push_data(&mut self, dt: (String, f64)) {
+    pub(crate) fn push_data(&mut self, dt: Sample) {
         self.data.push(dt);
     }
 
-    pub fn clear_data(&mut self) {
+    pub(crate) fn clear_data(&mut self) {
         self.data.clear();
     }
 }
 
-fn send_influx_value(dh: Arc<Mutex<DataHolder>>, id: &'static str, params: Option<Vec<(&str, &str)>>, val: f64) {
-    let mut comb : String;
-    if let Some(params) = params {
-        comb = params.iter().fold(String::new(), |acc, &(k,v)| acc + k + "=\"" + v + "\", ");
-        comb.pop();
-        comb.pop();
-    } else {
-        comb = String::new();
+/// Escapes a Prometheus label value: backslash, double-quote and newline
+/// must be escaped for the line to parse as valid exposition format.
+fn escape_label_value(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders the current samples as spec-compliant Prometheus text exposition
+/// format: one `# HELP`/`# TYPE` pair per metric name, followed by its samples.
+fn render_prometheus(data: &[Sample]) -> String {
+    let mut out = String::new();
+    let mut names : Vec<&str> = vec![];
+    for sample in data {
+        if !names.contains(&sample.name) {
+            names.push(sample.name);
+        }
+    }
+    for name in names {
+        let mut emitted_header = false;
+        for sample in data.iter().filter(|s| s.name == name) {
+            if !emitted_header {
+                out.push_str(&format!("# HELP {} {}\n", sample.name, escape_label_value(&sample.help)));
+                out.push_str(&format!("# TYPE {} {}\n", sample.name, sample.kind));
+                emitted_header = true;
+            }
+            let labels = sample.labels.iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+                .collect::<Vec<String>>()
+                .join(",");
+            if labels.is_empty() {
+                out.push_str(&format!("{} {}\n", sample.name, sample.value));
+            } else {
+                out.push_str(&format!("{}{{{}}} {}\n", sample.name, labels, sample.value));
+            }
+        }
     }
+    out
+}
+
+pub(crate) fn send_influx_value(dh: Arc<Mutex<DataHolder>>, id: &'static str, params: Option<Vec<(&str, &str)>>, val: f64) {
+    send_influx_value_with_meta(dh, id, metric_kind(id), metric_help(id).to_string(), params, val);
+}
+
+/// Like [`send_influx_value`], but with an explicit kind/HELP text instead of
+/// the built-in lookup, for metrics whose metadata comes from a TOML
+/// [`CollectorConfig`] rather than being known at compile time.
+pub(crate) fn send_influx_value_with_meta(dh: Arc<Mutex<DataHolder>>, id: &'static str, kind: MetricKind, help: String, params: Option<Vec<(&str, &str)>>, val: f64) {
+    let labels = params.unwrap_or_default().into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
     task::block_on(async move {
         let mut lock = dh.lock().await;
-        let itm = format!("{}{{{}}}", id, comb);
-        (*lock).push_data((itm, val));
+        (*lock).push_data(Sample { name: id, labels, value: val, kind, help });
     });
 }
 
+#[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
 fn handle_cpu_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
     let parts = line.split(" ");
     let mut prev = "";
@@ -88,6 +212,7 @@ fn handle_cpu_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String>
     Ok(())
 }
 
+#[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
 fn handle_mem_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
     let parts = line.split(" ");
     let mut prev = "";
@@ -114,6 +239,7 @@ fn handle_mem_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String>
     Ok(())    
 }
 
+#[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
 fn handle_proc_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
     let line = line.trim();
     let line_items : Vec<&str> = line.split(" ").filter(|&v| v != "").collect();
@@ -138,6 +264,7 @@ fn handle_proc_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String
     Ok(())
 }
 
+#[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
 async fn handle_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
     if line.starts_with("%Cpu(s):") {
         return handle_cpu_line(line, dh);
@@ -154,8 +281,16 @@ async fn handle_line(line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), Strin
     Ok(())
 }
 
-async fn exec_top_command(dh: Arc<Mutex<DataHolder>>) {
-    let output = Command::new("top").arg("-b").arg("-n").arg("1").arg("-w").arg("512").output().unwrap();
+#[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
+async fn exec_top_command(dh: Arc<Mutex<DataHolder>>, logger: SharedLogger) {
+    let output = match Command::new("top").arg("-b").arg("-n").arg("1").arg("-w").arg("512").output() {
+        Ok(output) => output,
+        Err(e) => {
+            logger::log_error(&logger, format!("Unable to run 'top': {}", e));
+            send_influx_value(dh, COLLECTOR_ERROR_ID, Some(vec![("collector", "top")]), 1.0);
+            return;
+        },
+    };
     if output.status.success() {
         let lines = output.stdout.split(|&el| el == 10);
         let mut tasks = FuturesUnordered::new();
@@ -165,21 +300,22 @@ async fn exec_top_command(dh: Arc<Mutex<DataHolder>>) {
         }
         while let Some(result) = tasks.next().await {
             if let Err(emsg) = result {
-                eprintln!("Line subcommand error: {}", emsg);
+                logger::log_warn(&logger, format!("Line subcommand error: {}", emsg));
             }
         }
     } else {
         let stderr = match str::from_utf8(&output.stderr) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Unable to convert stderr to UTF-8 string: {}", e);
+                logger::log_error(&logger, format!("Unable to convert stderr to UTF-8 string: {}", e));
                 return;
             },
         };
-        eprintln!("Command error: {}", stderr);
+        logger::log_error(&logger, format!("Command error: {}", stderr));
     }
 }
 
+#[cfg(all(feature = "collector-netstat", not(feature = "collector-proc")))]
 async fn handle_netstat_line(header: &str, line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
     let line = line.trim();
     let numbers = line.chars().map(|v| {
@@ -208,8 +344,16 @@ async fn handle_netstat_line(header: &str, line: &str, dh: Arc<Mutex<DataHolder>
     Ok(())
 }
 
-async fn exec_netstat_command(dh: Arc<Mutex<DataHolder>>) {
-    let output = Command::new("netstat").arg("-s").output().unwrap();
+#[cfg(all(feature = "collector-netstat", not(feature = "collector-proc")))]
+async fn exec_netstat_command(dh: Arc<Mutex<DataHolder>>, logger: SharedLogger) {
+    let output = match Command::new("netstat").arg("-s").output() {
+        Ok(output) => output,
+        Err(e) => {
+            logger::log_error(&logger, format!("Unable to run 'netstat': {}", e));
+            send_influx_value(dh, COLLECTOR_ERROR_ID, Some(vec![("collector", "netstat")]), 1.0);
+            return;
+        },
+    };
     if output.status.success() {
         let lines = output.stdout.split(|&el| el == 10);
         let mut tasks = FuturesUnordered::new();
@@ -227,24 +371,169 @@ async fn exec_netstat_command(dh: Arc<Mutex<DataHolder>>) {
         }
         while let Some(result) = tasks.next().await {
             if let Err(emsg) = result {
-                eprintln!("Line subcommand error NETSTAT: {}", emsg);
+                logger::log_warn(&logger, format!("Line subcommand error NETSTAT: {}", emsg));
             }
         }
     } else {
         let stderr = match str::from_utf8(&output.stderr) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("Unable to convert NETSTAT stderr to UTF-8 string: {}", e);
+                logger::log_error(&logger, format!("Unable to convert NETSTAT stderr to UTF-8 string: {}", e));
                 return;
             },
         };
-        eprintln!("Command error: {}", stderr);
+        logger::log_error(&logger, format!("Command error: {}", stderr));
+    }
+}
+
+fn handle_configured_line(cfg: &'static CollectorConfig, line: &str, dh: Arc<Mutex<DataHolder>>) -> Result<(), String> {
+    if !cfg.matcher.matches(line, cfg.compiled_matcher.as_ref()) {
+        return Ok(());
     }
+    let re = cfg.compiled_pattern.as_ref()
+        .ok_or_else(|| format!("Collector '{}' has no compiled pattern", cfg.name))?;
+    let caps = match re.captures(line) {
+        Some(caps) => caps,
+        None => return Ok(()),
+    };
+
+    let mut labels : Vec<(&str, &str)> = vec![];
+    for label in &cfg.labels {
+        let matched = caps.name(&label.capture)
+            .ok_or_else(|| format!("Collector '{}' pattern has no capture named '{}'", cfg.name, label.capture))?;
+        labels.push((&label.label, matched.as_str()));
+    }
+
+    let value_raw = caps.name(&cfg.value.capture)
+        .ok_or_else(|| format!("Collector '{}' pattern has no capture named '{}'", cfg.name, cfg.value.capture))?
+        .as_str();
+    let conversion = Conversion::from_str(&cfg.value.conversion)?;
+    let value = conversion.apply(value_raw)?;
+
+    let kind = MetricKind::from_str(&cfg.kind).unwrap_or(MetricKind::Gauge);
+    send_influx_value_with_meta(dh, cfg.metric_id.as_str(), kind, cfg.help.clone(), Some(labels), value);
+    Ok(())
+}
+
+async fn exec_configured_collector(cfg: &'static CollectorConfig, dh: Arc<Mutex<DataHolder>>, logger: SharedLogger) {
+    let output = match Command::new(&cfg.command).args(&cfg.args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            logger::log_error(&logger, format!("Collector '{}' failed to run '{}': {}", cfg.name, cfg.command, e));
+            send_influx_value(dh, COLLECTOR_ERROR_ID, Some(vec![("collector", &cfg.name)]), 1.0);
+            return;
+        },
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        logger::log_error(&logger, format!("Collector '{}' command error: {}", cfg.name, stderr));
+        return;
+    }
+    let lines = output.stdout.split(|&el| el == 10);
+    let mut tasks = FuturesUnordered::new();
+    for line in lines {
+        let line = String::from_utf8_lossy(line).into_owned();
+        let dh = dh.clone();
+        tasks.push(async move { handle_configured_line(cfg, &line, dh) });
+    }
+    while let Some(result) = tasks.next().await {
+        if let Err(emsg) = result {
+            logger::log_warn(&logger, format!("Collector '{}' line error: {}", cfg.name, emsg));
+        }
+    }
+}
+
+type CollectorFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// State shared by the native `/proc` collectors across scrape cycles, so
+/// jiffy-based percentages can be computed by differencing consecutive
+/// samples.
+#[allow(dead_code)]
+struct ProcCollectorState {
+    cpu_sampler: Arc<Mutex<proc_collectors::CpuSampler>>,
+    proc_sampler: Arc<Mutex<proc_collectors::ProcSampler>>,
+    clk_tck: f64,
+}
+
+/// Builds one boxed future per enabled collector for this scrape cycle.
+/// Which collectors are compiled in is controlled by Cargo features
+/// ("collector-top", "collector-netstat", "collector-proc"), so a minimal
+/// build doesn't hard-require `top`/`netstat` to be installed. `collector-proc`
+/// reads the same CPU/memory/netstat data natively, so it takes precedence
+/// over `collector-top`/`collector-netstat` whenever it's enabled — running
+/// both at once would emit duplicate series for the same metric ids.
+#[cfg_attr(not(feature = "collector-proc"), allow(unused_variables))]
+fn build_collector_registry(
+    dh: Arc<Mutex<DataHolder>>,
+    logger: SharedLogger,
+    configured: &'static [CollectorConfig],
+    proc_state: &ProcCollectorState,
+) -> Vec<CollectorFuture> {
+    let mut registry : Vec<CollectorFuture> = vec![];
+
+    #[cfg(all(feature = "collector-top", not(feature = "collector-proc")))]
+    registry.push(Box::pin(exec_top_command(dh.clone(), logger.clone())));
+
+    #[cfg(all(feature = "collector-netstat", not(feature = "collector-proc")))]
+    registry.push(Box::pin(exec_netstat_command(dh.clone(), logger.clone())));
+
+    #[cfg(feature = "collector-proc")]
+    {
+        registry.push(Box::pin(proc_collectors::exec_proc_cpu_collector(dh.clone(), proc_state.cpu_sampler.clone(), logger.clone())));
+        registry.push(Box::pin(proc_collectors::exec_proc_mem_collector(dh.clone(), logger.clone())));
+        registry.push(Box::pin(proc_collectors::exec_proc_process_collector(dh.clone(), proc_state.proc_sampler.clone(), proc_state.clk_tck, logger.clone())));
+        registry.push(Box::pin(proc_collectors::exec_proc_netstat_collector(dh.clone(), logger.clone())));
+    }
+
+    for cfg in configured {
+        registry.push(Box::pin(exec_configured_collector(cfg, dh.clone(), logger.clone())));
+    }
+
+    registry
 }
 
 fn main() {
+    let log_level = std::env::var("LOG_LEVEL").ok()
+        .and_then(|v| logger::LogLevel::from_str(&v).ok())
+        .unwrap_or(logger::LogLevel::Info);
+    let log_buffer_size = std::env::var("LOG_BUFFER_SIZE").ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(200);
+    let logger = logger::new_shared_logger(log_level, log_buffer_size);
+
+    let collectors_config = match config::load_collectors_config(COLLECTORS_CONFIG_PATH) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            logger::log_error(&logger, format!("Unable to load collectors config: {}", e));
+            config::CollectorsConfig { collectors: vec![] }
+        },
+    };
+    let collectors : &'static [CollectorConfig] = Vec::leak(collectors_config.collectors);
+
+    let remote_write_client = std::env::var("REMOTE_WRITE_URL").ok().map(|url| {
+        let max_attempts = std::env::var("REMOTE_WRITE_MAX_ATTEMPTS").ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(5);
+        let initial_backoff_ms = std::env::var("REMOTE_WRITE_BACKOFF_MS").ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(500);
+        Arc::new(remote_write::AsyncClient::new(remote_write::RemoteWriteConfig {
+            url,
+            max_attempts,
+            initial_backoff: Duration::from_millis(initial_backoff_ms),
+        }, logger.clone()))
+    });
+    let http_enabled = std::env::var("METRICS_HTTP_ENABLED").map(|v| v != "0").unwrap_or(true);
+
+    let proc_state = ProcCollectorState {
+        cpu_sampler: Arc::new(Mutex::new(proc_collectors::CpuSampler::new())),
+        proc_sampler: Arc::new(Mutex::new(proc_collectors::ProcSampler::new())),
+        clk_tck: proc_collectors::clk_tck(),
+    };
+
     let thread_lock_a = Arc::new(Mutex::new(Arc::new(Mutex::new(DataHolder::new()))));
     let thread_lock_b = thread_lock_a.clone();
+    let scrape_logger = logger.clone();
 
     task::spawn(async move {
         loop {
@@ -254,14 +543,25 @@ fn main() {
                     let mut inner_lock = (*lock).lock().await;
                     (*inner_lock).clear_data();
                 }
-                let top_handle = exec_top_command((*lock).clone());
-                let netstat_handle = exec_netstat_command((*lock).clone());
-                join!(top_handle, netstat_handle);
+                let registry = build_collector_registry((*lock).clone(), scrape_logger.clone(), collectors, &proc_state);
+                let mut tasks : FuturesUnordered<CollectorFuture> = registry.into_iter().collect();
+                while tasks.next().await.is_some() {}
+
+                if let Some(client) = &remote_write_client {
+                    let inner_lock = (*lock).lock().await;
+                    let body = remote_write::to_line_protocol((*inner_lock).get_data());
+                    client.send(body);
+                }
             }
             sleep(Duration::from_secs(5));
         }
     });
 
+    if !http_enabled {
+        loop {
+            sleep(Duration::from_secs(3600));
+        }
+    }
 
     let server = tiny_http::Server::http("0.0.0.0:8787").unwrap();
     loop {
@@ -274,18 +574,24 @@ fn main() {
                     let lock = thread_lock_b.lock().await;
                     let inner_lock = (*lock).lock().await;
                     let items = (*inner_lock).get_data();
-                    let mut ans = items.iter().fold(String::new(), move |acc, (k,v)| acc + &k + " " + &v.to_string() + "\n");
-                    ans.pop();
+                    let ans = render_prometheus(items);
+                    Response::from_string(ans).with_status_code(200)
+                });
+            } else if request.url() == "/logs" {
+                let logger = logger.clone();
+                resp = task::block_on(async move {
+                    let lock = logger.lock().await;
+                    let ans = logger::render_logs((*lock).records());
                     Response::from_string(ans).with_status_code(200)
                 });
             } else {
                 resp = Response::from_string("running ...").with_status_code(200);
             }
             if let Err(e) = request.respond(resp) {
-                eprintln!("Response error: {}", e)
+                logger::log_warn(&logger, format!("Response error: {}", e));
             }
         } else if let Err(e) = rq {
-            eprintln!("Request error: {}", e);
+            logger::log_warn(&logger, format!("Request error: {}", e));
         }
     }
 }
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+use async_std::sync::Mutex;
+use crate::{send_influx_value, DataHolder, CPU_IDLE_ID, CPU_NICE_ID, CPU_SYSTEM_ID, CPU_USER_ID,
+    MEM_BUFFERED_ID, MEM_FREE_ID, MEM_TOTAL_ID, MEM_USED_ID, NETSTAT_INFO_ID, PROC_CPU_ID, PROC_MEM_ID};
+use crate::logger::{self, SharedLogger};
+
+/// Ticks-per-second used to convert `/proc/<pid>/stat` jiffy counters into
+/// seconds of CPU time, as `sysconf(_SC_CLK_TCK)` would report.
+pub fn clk_tck() -> f64 {
+    let tck = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if tck > 0 { tck as f64 } else { 100.0 }
+}
+
+struct CpuJiffies {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+}
+
+fn read_cpu_jiffies() -> Result<CpuJiffies, String> {
+    let stat = fs::read_to_string("/proc/stat").map_err(|e| format!("Unable to read /proc/stat: {}", e))?;
+    let line = stat.lines().next().ok_or("Empty /proc/stat")?;
+    let fields : Vec<u64> = line.split_whitespace().skip(1)
+        .map(|v| v.parse::<u64>().unwrap_or(0))
+        .collect();
+    if fields.len() < 4 {
+        return Err("Malformed cpu line in /proc/stat".to_string());
+    }
+    Ok(CpuJiffies { user: fields[0], nice: fields[1], system: fields[2], idle: fields[3] })
+}
+
+/// Holds the previous `/proc/stat` sample so the CPU collector can turn
+/// cumulative jiffy counters into a percentage by differencing across the
+/// 5-second scrape interval.
+pub struct CpuSampler {
+    prev: Option<CpuJiffies>,
+}
+
+impl CpuSampler {
+    pub fn new() -> CpuSampler {
+        CpuSampler { prev: None }
+    }
+}
+
+#[allow(dead_code)]
+pub async fn exec_proc_cpu_collector(dh: Arc<Mutex<DataHolder>>, sampler: Arc<Mutex<CpuSampler>>, logger: SharedLogger) {
+    let current = match read_cpu_jiffies() {
+        Ok(v) => v,
+        Err(e) => {
+            logger::log_error(&logger, format!("CPU collector error: {}", e));
+            return;
+        },
+    };
+    let mut sampler = sampler.lock().await;
+    if let Some(prev) = sampler.prev.take() {
+        let total_delta = (current.user + current.nice + current.system + current.idle)
+            .saturating_sub(prev.user + prev.nice + prev.system + prev.idle);
+        if total_delta > 0 {
+            let pct = |delta: u64| (delta as f64 / total_delta as f64) * 100.0;
+            send_influx_value(dh.clone(), CPU_USER_ID, None, pct(current.user.saturating_sub(prev.user)));
+            send_influx_value(dh.clone(), CPU_NICE_ID, None, pct(current.nice.saturating_sub(prev.nice)));
+            send_influx_value(dh.clone(), CPU_SYSTEM_ID, None, pct(current.system.saturating_sub(prev.system)));
+            send_influx_value(dh.clone(), CPU_IDLE_ID, None, pct(current.idle.saturating_sub(prev.idle)));
+        }
+    }
+    sampler.prev = Some(current);
+}
+
+#[allow(dead_code)]
+pub async fn exec_proc_mem_collector(dh: Arc<Mutex<DataHolder>>, logger: SharedLogger) {
+    let meminfo = match fs::read_to_string("/proc/meminfo") {
+        Ok(v) => v,
+        Err(e) => {
+            logger::log_error(&logger, format!("Memory collector error: unable to read /proc/meminfo: {}", e));
+            return;
+        },
+    };
+    let mut values : HashMap<&str, f64> = HashMap::new();
+    for line in meminfo.lines() {
+        let mut parts = line.split_whitespace();
+        let key = match parts.next() {
+            Some(k) => k.trim_end_matches(':'),
+            None => continue,
+        };
+        if let Some(val) = parts.next().and_then(|v| v.parse::<f64>().ok()) {
+            values.insert(key, val / 1024.0);
+        }
+    }
+    let total = values.get("MemTotal").copied().unwrap_or(0.0);
+    let free = values.get("MemFree").copied().unwrap_or(0.0);
+    let buffered = values.get("Buffers").copied().unwrap_or(0.0) + values.get("Cached").copied().unwrap_or(0.0);
+    let used = total - free - buffered;
+    send_influx_value(dh.clone(), MEM_TOTAL_ID, None, total);
+    send_influx_value(dh.clone(), MEM_FREE_ID, None, free);
+    send_influx_value(dh.clone(), MEM_USED_ID, None, used);
+    send_influx_value(dh, MEM_BUFFERED_ID, None, buffered);
+}
+
+struct ProcTimes {
+    utime: u64,
+    stime: u64,
+    sampled_at: Instant,
+}
+
+fn read_proc_stat(pid: u32) -> Result<(String, u64, u64), String> {
+    let raw = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .map_err(|e| format!("Unable to read /proc/{}/stat: {}", pid, e))?;
+    let comm_start = raw.find('(').ok_or("Malformed /proc/<pid>/stat: no comm")?;
+    let comm_end = raw.rfind(')').ok_or("Malformed /proc/<pid>/stat: no comm")?;
+    let comm = raw[comm_start + 1..comm_end].to_string();
+    let rest : Vec<&str> = raw[comm_end + 2..].split_whitespace().collect();
+    let utime = rest.get(11).and_then(|v| v.parse::<u64>().ok())
+        .ok_or("Malformed /proc/<pid>/stat: missing utime")?;
+    let stime = rest.get(12).and_then(|v| v.parse::<u64>().ok())
+        .ok_or("Malformed /proc/<pid>/stat: missing stime")?;
+    Ok((comm, utime, stime))
+}
+
+fn read_proc_uid(pid: u32) -> Result<String, String> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))
+        .map_err(|e| format!("Unable to read /proc/{}/status: {}", pid, e))?;
+    for line in status.lines() {
+        if let Some(rest) = line.strip_prefix("Uid:") {
+            if let Some(uid) = rest.split_whitespace().next() {
+                return Ok(uid.to_string());
+            }
+        }
+    }
+    Err(format!("No Uid line in /proc/{}/status", pid))
+}
+
+fn read_proc_rss_mib(pid: u32) -> Result<f64, String> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))
+        .map_err(|e| format!("Unable to read /proc/{}/status: {}", pid, e))?;
+    for line in status.lines() {
+        if let Some(rest) = line.strip_prefix("VmRSS:") {
+            let kb = rest.trim().trim_end_matches("kB").trim().parse::<f64>()
+                .map_err(|e| format!("Unable to parse VmRSS for pid {}: {}", pid, e))?;
+            return Ok(kb / 1024.0);
+        }
+    }
+    Ok(0.0)
+}
+
+/// Holds the previous CPU-jiffy sample per pid so per-process CPU usage can
+/// be computed the same way as the system-wide figure.
+pub struct ProcSampler {
+    prev: HashMap<u32, ProcTimes>,
+}
+
+impl ProcSampler {
+    pub fn new() -> ProcSampler {
+        ProcSampler { prev: HashMap::new() }
+    }
+}
+
+#[allow(dead_code)]
+pub async fn exec_proc_process_collector(dh: Arc<Mutex<DataHolder>>, sampler: Arc<Mutex<ProcSampler>>, clk_tck: f64, logger: SharedLogger) {
+    let entries = match fs::read_dir("/proc") {
+        Ok(v) => v,
+        Err(e) => {
+            logger::log_error(&logger, format!("Process collector error: unable to read /proc: {}", e));
+            return;
+        },
+    };
+    let mut sampler = sampler.lock().await;
+    let prev = std::mem::take(&mut sampler.prev);
+    let mut current = HashMap::new();
+    let now = Instant::now();
+    for entry in entries.flatten() {
+        let pid : u32 = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let (comm, utime, stime) = match read_proc_stat(pid) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let uid = read_proc_uid(pid).unwrap_or_else(|_| "?".to_string());
+        let rss = read_proc_rss_mib(pid).unwrap_or(0.0);
+        let pid_str = pid.to_string();
+        let labels = vec![("pid", pid_str.as_str()), ("user", uid.as_str()), ("command", comm.as_str())];
+        send_influx_value(dh.clone(), PROC_MEM_ID, Some(labels.clone()), rss);
+        if let Some(prev_times) = prev.get(&pid) {
+            let elapsed = now.duration_since(prev_times.sampled_at).as_secs_f64();
+            if elapsed > 0.0 {
+                let delta = (utime + stime).saturating_sub(prev_times.utime + prev_times.stime);
+                let cpu_pct = (delta as f64 / clk_tck) / elapsed * 100.0;
+                send_influx_value(dh.clone(), PROC_CPU_ID, Some(labels), cpu_pct);
+            }
+        }
+        current.insert(pid, ProcTimes { utime, stime, sampled_at: now });
+    }
+    sampler.prev = current;
+}
+
+fn parse_snmp_style_file(path: &str) -> Result<Vec<(String, String, f64)>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    let mut lines = content.lines();
+    let mut out = vec![];
+    while let (Some(header), Some(values)) = (lines.next(), lines.next()) {
+        let mut header_fields = header.split_whitespace();
+        let category = match header_fields.next() {
+            Some(c) => c.trim_end_matches(':').to_string(),
+            None => continue,
+        };
+        let field_names : Vec<&str> = header_fields.collect();
+        let mut value_fields = values.split_whitespace();
+        let value_category = value_fields.next().unwrap_or("").trim_end_matches(':');
+        if value_category != category {
+            continue;
+        }
+        for (name, raw) in field_names.iter().zip(value_fields) {
+            if let Ok(v) = raw.parse::<f64>() {
+                out.push((category.clone(), name.to_string(), v));
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[allow(dead_code)]
+pub async fn exec_proc_netstat_collector(dh: Arc<Mutex<DataHolder>>, logger: SharedLogger) {
+    for path in ["/proc/net/snmp", "/proc/net/netstat"] {
+        match parse_snmp_style_file(path) {
+            Ok(entries) => {
+                for (category, desc, value) in entries {
+                    send_influx_value(dh.clone(), NETSTAT_INFO_ID, Some(vec![("category", &category), ("desc", &desc)]), value);
+                }
+            },
+            Err(e) => logger::log_warn(&logger, format!("Netstat collector error reading {}: {}", path, e)),
+        }
+    }
+}
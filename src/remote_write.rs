@@ -0,0 +1,89 @@
+use std::time::Duration;
+use async_std::task;
+use crate::Sample;
+use crate::logger::{self, SharedLogger};
+
+/// Settings for the optional push/remote-write subsystem.
+pub struct RemoteWriteConfig {
+    pub url: String,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+}
+
+/// Ships scrape snapshots to a remote time-series backend without blocking
+/// the scrape loop: `send` hands the batch to a detached task that retries
+/// with exponential backoff and drops the batch after `max_attempts` so a
+/// dead backend never stalls scraping.
+pub struct AsyncClient {
+    config: RemoteWriteConfig,
+    logger: SharedLogger,
+}
+
+impl AsyncClient {
+    pub fn new(config: RemoteWriteConfig, logger: SharedLogger) -> AsyncClient {
+        AsyncClient { config, logger }
+    }
+
+    pub fn send(&self, body: String) {
+        let url = self.config.url.clone();
+        let max_attempts = self.config.max_attempts;
+        let initial_backoff = self.config.initial_backoff;
+        let logger = self.logger.clone();
+        task::spawn(async move {
+            if let Err(e) = send_with_retry(&url, &body, max_attempts, initial_backoff, &logger).await {
+                logger::log_error(&logger, format!("Remote write to '{}' failed permanently: {}", url, e));
+            }
+        });
+    }
+}
+
+async fn send_with_retry(url: &str, body: &str, max_attempts: u32, initial_backoff: Duration, logger: &SharedLogger) -> Result<(), String> {
+    let mut backoff = initial_backoff;
+    let mut attempt = 0u32;
+    loop {
+        attempt += 1;
+        match surf::post(url).body_string(body.to_string()).await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => logger::log_warn(logger, format!("Remote write attempt {} to '{}' got status {}", attempt, url, resp.status())),
+            Err(e) => logger::log_warn(logger, format!("Remote write attempt {} to '{}' error: {}", attempt, url, e)),
+        }
+        if attempt >= max_attempts {
+            return Err(format!("gave up after {} attempts", attempt));
+        }
+        task::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Escapes a tag or field value for InfluxDB line protocol: comma, space
+/// and equals sign must be backslash-escaped outside of quoted strings.
+fn escape_line_protocol_value(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            ',' | ' ' | '=' => {
+                out.push('\\');
+                out.push(c);
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Serializes the current samples into InfluxDB line protocol, one line per
+/// sample: `measurement,tag=value,... value=<float>`.
+pub fn to_line_protocol(data: &[Sample]) -> String {
+    let mut out = String::new();
+    for sample in data {
+        out.push_str(&escape_line_protocol_value(sample.name));
+        for (k, v) in &sample.labels {
+            out.push(',');
+            out.push_str(&escape_line_protocol_value(k));
+            out.push('=');
+            out.push_str(&escape_line_protocol_value(v));
+        }
+        out.push_str(&format!(" value={}\n", sample.value));
+    }
+    out
+}